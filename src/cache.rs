@@ -0,0 +1,233 @@
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+
+use failure::Error;
+
+use super::{IP2Location, IP2LocationRecord, RecordFields};
+
+/// Hit/miss counters for a `CachedIP2Location`'s lookup cache.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CacheStats {
+    pub hits: usize,
+    pub misses: usize,
+}
+
+#[derive(Debug)]
+struct IntervalLruCache {
+    capacity: usize,
+    ranges: Vec<(u128, u128)>,
+    entries: HashMap<(u128, u128), Arc<IP2LocationRecord>>,
+    order: VecDeque<(u128, u128)>,
+    stats: CacheStats,
+}
+
+impl IntervalLruCache {
+    fn new(capacity: usize) -> IntervalLruCache {
+        IntervalLruCache {
+            capacity: capacity,
+            ranges: Vec::new(),
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+            stats: CacheStats::default(),
+        }
+    }
+
+    fn find_range(&self, ipno: u128) -> Option<(u128, u128)> {
+        match self.ranges.binary_search_by(|&(from, _)| from.cmp(&ipno)) {
+            Ok(i) => Some(self.ranges[i]),
+            Err(0) => None,
+            Err(i) => {
+                let (from, to) = self.ranges[i - 1];
+                if from <= ipno && ipno < to {
+                    Some((from, to))
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    fn get(&mut self, ipno: u128) -> Option<Arc<IP2LocationRecord>> {
+        let key = self.find_range(ipno);
+        match key {
+            Some(key) => {
+                self.stats.hits += 1;
+                self.order.retain(|k| *k != key);
+                self.order.push_back(key);
+                self.entries.get(&key).cloned()
+            }
+            None => {
+                self.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    fn insert(&mut self, from: u128, to: u128, record: Arc<IP2LocationRecord>) {
+        let key = (from, to);
+        if !self.entries.contains_key(&key) {
+            let idx = self.ranges.binary_search_by(|&(f, _)| f.cmp(&from)).unwrap_or_else(|i| i);
+            self.ranges.insert(idx, key);
+        }
+        self.entries.insert(key, record);
+        self.order.retain(|k| *k != key);
+        self.order.push_back(key);
+
+        while self.entries.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+                if let Ok(idx) = self.ranges.binary_search_by(|&(f, _)| f.cmp(&oldest.0)) {
+                    self.ranges.remove(idx);
+                }
+            } else {
+                break;
+            }
+        }
+    }
+}
+
+fn ip_pair_to_u128(from: IpAddr, to: IpAddr) -> Option<(u128, u128)> {
+    match (from, to) {
+        (IpAddr::V4(f), IpAddr::V4(t)) => Some((u32::from(f) as u128, u32::from(t) as u128)),
+        (IpAddr::V6(f), IpAddr::V6(t)) => Some((u128::from(f), u128::from(t))),
+        _ => None,
+    }
+}
+
+/// Clears every column not selected by `fields`, leaving `ip`, `ip_from`,
+/// `ip_to` and `cidr` untouched (those aren't gated by `RecordFields`).
+fn select_fields(rec: &IP2LocationRecord, fields: RecordFields) -> IP2LocationRecord {
+    let mut out = rec.clone();
+    if !fields.contains(RecordFields::COUNTRY) {
+        out.country_short = None;
+        out.country_long = None;
+    }
+    if !fields.contains(RecordFields::REGION) {
+        out.region = None;
+    }
+    if !fields.contains(RecordFields::CITY) {
+        out.city = None;
+    }
+    if !fields.contains(RecordFields::ISP) {
+        out.isp = None;
+    }
+    if !fields.contains(RecordFields::LATITUDE) {
+        out.latitude = None;
+    }
+    if !fields.contains(RecordFields::LONGITUDE) {
+        out.longitude = None;
+    }
+    if !fields.contains(RecordFields::DOMAIN) {
+        out.domain = None;
+    }
+    if !fields.contains(RecordFields::ZIPCODE) {
+        out.zipcode = None;
+    }
+    if !fields.contains(RecordFields::TIMEZONE) {
+        out.timezone = None;
+    }
+    if !fields.contains(RecordFields::NETSPEED) {
+        out.netspeed = None;
+    }
+    if !fields.contains(RecordFields::IDDCODE) {
+        out.iddcode = None;
+    }
+    if !fields.contains(RecordFields::AREACODE) {
+        out.area_code = None;
+    }
+    if !fields.contains(RecordFields::WEATHERSTATIONCODE) {
+        out.weather_code = None;
+    }
+    if !fields.contains(RecordFields::WEATHERSTATIONNAME) {
+        out.weather_name = None;
+    }
+    if !fields.contains(RecordFields::MCC) {
+        out.mcc = None;
+    }
+    if !fields.contains(RecordFields::MNC) {
+        out.mnc = None;
+    }
+    if !fields.contains(RecordFields::MOBILEBRAND) {
+        out.mobile_brand = None;
+    }
+    if !fields.contains(RecordFields::ELEVATION) {
+        out.elevation = None;
+    }
+    if !fields.contains(RecordFields::USAGETYPE) {
+        out.usage_type = None;
+    }
+    out
+}
+
+/// Wraps an `IP2Location` with an opt-in LRU cache keyed by the resolved
+/// `[ip_from, ip_to)` block range. Once a range has been resolved, any
+/// further query that falls inside it is served from memory instead of
+/// re-running the binary search and re-decoding the row.
+///
+/// Built via `IP2Location::with_cache`. Uses `Mutex` rather than `RefCell`
+/// for interior mutability so the cache (like the `IP2Location` it wraps)
+/// stays `Sync` and can be shared across worker threads behind an `Arc`.
+#[derive(Debug)]
+pub struct CachedIP2Location {
+    inner: IP2Location,
+    v4_cache: Mutex<IntervalLruCache>,
+    v6_cache: Mutex<IntervalLruCache>,
+}
+
+impl CachedIP2Location {
+    pub(crate) fn new(inner: IP2Location, capacity: usize) -> CachedIP2Location {
+        CachedIP2Location {
+            inner: inner,
+            v4_cache: Mutex::new(IntervalLruCache::new(capacity)),
+            v6_cache: Mutex::new(IntervalLruCache::new(capacity)),
+        }
+    }
+
+    pub fn get_record(&self, ip_str: &str) -> Result<Option<IP2LocationRecord>, Error> {
+        self.get_record_with(ip_str, RecordFields::all())
+    }
+
+    pub fn get_record_with(&self, ip_str: &str, fields: RecordFields) -> Result<Option<IP2LocationRecord>, Error> {
+        let ipaddr = ip_str.parse::<IpAddr>()?;
+        // An IPv6 address that embeds an IPv4 one (IPv4-mapped, 6to4,
+        // Teredo, ...) is resolved against the IPv4 table by
+        // `IP2Location::get_record_with`, which returns a small IPv4
+        // `ip_from`/`ip_to` range. Key the cache bucket the same way here,
+        // or a range stored under the v6 bucket would never match the
+        // (much larger) original v6 address on a later lookup.
+        let (cache, ipno) = match ipaddr {
+            IpAddr::V4(v4) => (&self.v4_cache, u32::from(v4) as u128),
+            IpAddr::V6(v6) => match self.inner.embedded_ipv4_for(v6) {
+                Some(v4) => (&self.v4_cache, u32::from(v4) as u128),
+                None => (&self.v6_cache, u128::from(v6)),
+            },
+        };
+
+        if let Some(full) = cache.lock().unwrap().get(ipno) {
+            return Ok(Some(select_fields(&full, fields)));
+        }
+
+        // Always resolve the full record so that later queries for a wider
+        // field selection over the same range can still be served from
+        // the cache.
+        let record = self.inner.get_record_with(ip_str, RecordFields::all())?;
+
+        if let Some(ref full) = record {
+            if let (Some(from_str), Some(to_str)) = (full.ip_from.as_ref(), full.ip_to.as_ref()) {
+                if let (Ok(from_ip), Ok(to_ip)) = (from_str.parse::<IpAddr>(), to_str.parse::<IpAddr>()) {
+                    if let Some((from, to)) = ip_pair_to_u128(from_ip, to_ip) {
+                        cache.lock().unwrap().insert(from, to, Arc::new(full.clone()));
+                    }
+                }
+            }
+        }
+
+        Ok(record.map(|full| select_fields(&full, fields)))
+    }
+
+    /// Returns `(v4_stats, v6_stats)` hit/miss counters for the cache.
+    pub fn cache_stats(&self) -> (CacheStats, CacheStats) {
+        (self.v4_cache.lock().unwrap().stats, self.v6_cache.lock().unwrap().stats)
+    }
+}
@@ -5,23 +5,181 @@ extern crate num_traits;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
+#[cfg(feature = "async")]
+extern crate tokio;
 
 use std::default::Default;
 use std::fs::File;
 use std::io::Cursor;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 
-use byteorder::{LittleEndian, ReadBytesExt};
+use byteorder::{ByteOrder, LittleEndian, ReadBytesExt};
 use failure::{Error, err_msg};
 use memmap::Mmap;
 use num_traits::PrimInt;
 
+mod cache;
 mod positions;
+mod source;
+
+pub use cache::{CacheStats, CachedIP2Location};
+pub use source::DbSource;
+use source::{BytesSource, MmapSource};
+
+/// A bitflag-style selector for which columns `get_record_with` should decode.
+///
+/// Unselected columns skip both the pointer dereference and the UTF-8
+/// decode, so callers that only need a handful of fields (e.g.
+/// `RecordFields::COUNTRY`) avoid the cost of materializing the rest of the
+/// row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordFields(u32);
+
+impl RecordFields {
+    pub const COUNTRY: RecordFields = RecordFields(1 << 0);
+    pub const REGION: RecordFields = RecordFields(1 << 1);
+    pub const CITY: RecordFields = RecordFields(1 << 2);
+    pub const ISP: RecordFields = RecordFields(1 << 3);
+    pub const LATITUDE: RecordFields = RecordFields(1 << 4);
+    pub const LONGITUDE: RecordFields = RecordFields(1 << 5);
+    pub const DOMAIN: RecordFields = RecordFields(1 << 6);
+    pub const ZIPCODE: RecordFields = RecordFields(1 << 7);
+    pub const TIMEZONE: RecordFields = RecordFields(1 << 8);
+    pub const NETSPEED: RecordFields = RecordFields(1 << 9);
+    pub const IDDCODE: RecordFields = RecordFields(1 << 10);
+    pub const AREACODE: RecordFields = RecordFields(1 << 11);
+    pub const WEATHERSTATIONCODE: RecordFields = RecordFields(1 << 12);
+    pub const WEATHERSTATIONNAME: RecordFields = RecordFields(1 << 13);
+    pub const MCC: RecordFields = RecordFields(1 << 14);
+    pub const MNC: RecordFields = RecordFields(1 << 15);
+    pub const MOBILEBRAND: RecordFields = RecordFields(1 << 16);
+    pub const ELEVATION: RecordFields = RecordFields(1 << 17);
+    pub const USAGETYPE: RecordFields = RecordFields(1 << 18);
+
+    /// Shorthand for `LATITUDE | LONGITUDE`.
+    pub const COORDS: RecordFields = RecordFields(Self::LATITUDE.0 | Self::LONGITUDE.0);
+
+    /// All known fields, matching the behaviour of `get_record`.
+    pub fn all() -> RecordFields {
+        RecordFields(!0)
+    }
+
+    /// No fields at all (only `ip`, `ip_from`, `ip_to` and `cidr` are
+    /// populated).
+    pub fn empty() -> RecordFields {
+        RecordFields(0)
+    }
+
+    pub fn contains(self, other: RecordFields) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl std::ops::BitOr for RecordFields {
+    type Output = RecordFields;
+
+    fn bitor(self, rhs: RecordFields) -> RecordFields {
+        RecordFields(self.0 | rhs.0)
+    }
+}
+
+impl std::ops::BitOrAssign for RecordFields {
+    fn bitor_assign(&mut self, rhs: RecordFields) {
+        self.0 |= rhs.0;
+    }
+}
+
+/// Recovers an embedded IPv4 address from an IPv6 address that is really an
+/// IPv4 address in v6 clothing: IPv4-mapped (`::ffff:a.b.c.d`),
+/// IPv4-compatible (`::a.b.c.d`), 6to4 (`2002::/16`), or Teredo
+/// (`2001:0000::/32`). Returns `None` for a genuine native IPv6 address.
+fn embedded_ipv4(addr: Ipv6Addr) -> Option<Ipv4Addr> {
+    let segments = addr.segments();
+    let octets = addr.octets();
+
+    // IPv4-mapped: ::ffff:a.b.c.d
+    if segments[0..5] == [0, 0, 0, 0, 0] && segments[5] == 0xffff {
+        return Some(Ipv4Addr::new(octets[12], octets[13], octets[14], octets[15]));
+    }
+
+    // IPv4-compatible: ::a.b.c.d (excluding the reserved ::0 and ::1)
+    if segments[0..6] == [0, 0, 0, 0, 0, 0] && octets[12..16] != [0, 0, 0, 0] && octets[12..16] != [0, 0, 0, 1] {
+        return Some(Ipv4Addr::new(octets[12], octets[13], octets[14], octets[15]));
+    }
+
+    // 6to4: 2002::/16, embedded address is bits 16-47
+    if segments[0] == 0x2002 {
+        return Some(Ipv4Addr::new(octets[2], octets[3], octets[4], octets[5]));
+    }
+
+    // Teredo: 2001:0000::/32, client IPv4 is XOR-obfuscated in the last 32 bits
+    if segments[0] == 0x2001 && segments[1] == 0x0000 {
+        return Some(Ipv4Addr::new(
+            octets[12] ^ 0xff,
+            octets[13] ^ 0xff,
+            octets[14] ^ 0xff,
+            octets[15] ^ 0xff,
+        ));
+    }
+
+    None
+}
+
+/// Collapses `[from, to_exclusive)` into the minimal set of CIDR blocks,
+/// expressed as `(block_start, prefix_len)` pairs.
+fn range_to_cidr_prefixes(from: u128, to_exclusive: u128, addr_bits: u32) -> Vec<(u128, u32)> {
+    let mut blocks = Vec::new();
+    let mut start = from;
+    while start < to_exclusive {
+        let remaining = to_exclusive - start;
+        let align_bits = if start == 0 { addr_bits } else { start.trailing_zeros().min(addr_bits) };
+
+        let mut size_bits = align_bits;
+        loop {
+            let block_size = 1u128.checked_shl(size_bits).unwrap_or(u128::max_value());
+            if size_bits == 0 || block_size <= remaining {
+                break;
+            }
+            size_bits -= 1;
+        }
+
+        let block_size = 1u128.checked_shl(size_bits).unwrap_or(u128::max_value());
+        blocks.push((start, addr_bits - size_bits));
+        start = start.saturating_add(block_size);
+    }
+    blocks
+}
+
+/// Formats `[from, to_exclusive)` as one or more IPv4 CIDR blocks, joined by
+/// `", "` when the range doesn't collapse to a single aligned prefix.
+fn format_cidr_v4(from: u32, to_exclusive: u32) -> Option<String> {
+    let blocks = range_to_cidr_prefixes(from as u128, to_exclusive as u128, 32);
+    if blocks.is_empty() {
+        return None;
+    }
+    Some(blocks.into_iter()
+        .map(|(start, prefix)| format!("{}/{}", Ipv4Addr::from(start as u32), prefix))
+        .collect::<Vec<_>>()
+        .join(", "))
+}
+
+/// Formats `[from, to_exclusive)` as one or more IPv6 CIDR blocks, joined by
+/// `", "` when the range doesn't collapse to a single aligned prefix.
+fn format_cidr_v6(from: u128, to_exclusive: u128) -> Option<String> {
+    let blocks = range_to_cidr_prefixes(from, to_exclusive, 128);
+    if blocks.is_empty() {
+        return None;
+    }
+    Some(blocks.into_iter()
+        .map(|(start, prefix)| format!("{}/{}", Ipv6Addr::from(start), prefix))
+        .collect::<Vec<_>>()
+        .join(", "))
+}
 
 #[derive(Debug)]
 pub struct IP2Location {
     db_path: String,
-    db_buffer: Mmap,
+    db_buffer: Box<dyn DbSource>,
     db_type: usize,
     db_column: usize,
     db_year: usize,
@@ -36,11 +194,63 @@ pub struct IP2Location {
 }
 
 impl IP2Location {
+    /// Opens a `.BIN` database by memory-mapping it. Equivalent to
+    /// `open_mmap`; kept as the default entry point for backward
+    /// compatibility.
     pub fn open(db_path: &str) -> Result<IP2Location, Error> {
+        Self::open_mmap(db_path)
+    }
+
+    /// Opens a `.BIN` database by memory-mapping it.
+    pub fn open_mmap(db_path: &str) -> Result<IP2Location, Error> {
         let file = File::open(db_path)?;
-        let db_buffer = unsafe { Mmap::map(&file)? };
+        let mmap = unsafe { Mmap::map(&file)? };
+        Self::from_source(db_path, MmapSource(mmap))
+    }
 
-        let mut cursor = Cursor::new(db_buffer);
+    /// Opens a `.BIN` database already held in memory, e.g. one embedded in
+    /// the binary with `include_bytes!` or fetched over the network.
+    pub fn open_bytes(db_path: &str, bytes: Vec<u8>) -> Result<IP2Location, Error> {
+        Self::from_source(db_path, BytesSource(bytes))
+    }
+
+    /// Loads a `.BIN` database from an async reader via a seek-and-read
+    /// loop, then serves queries out of an in-memory buffer. Lets callers
+    /// stream a database in without holding a file descriptor open for the
+    /// lifetime of the `IP2Location`.
+    #[cfg(feature = "async")]
+    pub async fn open_reader<R>(db_path: &str, mut reader: R) -> Result<IP2Location, Error>
+        where R: tokio::io::AsyncRead + tokio::io::AsyncSeek + Unpin {
+        use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+        reader.seek(std::io::SeekFrom::Start(0)).await?;
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        Self::open_bytes(db_path, bytes)
+    }
+
+    /// Wraps this database in an LRU cache keyed by the resolved
+    /// `[ip_from, ip_to)` block range, so repeated lookups of nearby
+    /// addresses skip both the lookup and the decoding.
+    pub fn with_cache(self, capacity: usize) -> CachedIP2Location {
+        CachedIP2Location::new(self, capacity)
+    }
+
+    /// Mirrors the IPv4-redirect decision `get_record_with` makes for a V6
+    /// query, so callers that need to pick a cache bucket *before* resolving
+    /// the record (i.e. `CachedIP2Location`) key it the same way the actual
+    /// lookup will.
+    pub(crate) fn embedded_ipv4_for(&self, addr: Ipv6Addr) -> Option<Ipv4Addr> {
+        if self.ipv4_db_count > 0 {
+            embedded_ipv4(addr)
+        } else {
+            None
+        }
+    }
+
+    fn from_source<S: DbSource + 'static>(db_path: &str, source: S) -> Result<IP2Location, Error> {
+        let header = source.read_at(0, 64)?;
+        let mut cursor = Cursor::new(header.as_ref());
         let db_type = cursor.read_u8()? as usize;
         let db_column = cursor.read_u8()? as usize;
         let db_year = cursor.read_u8()? as usize;
@@ -53,11 +263,9 @@ impl IP2Location {
         let ipv4_index_base_addr = cursor.read_u32::<LittleEndian>()? as usize;
         let ipv6_index_base_addr = cursor.read_u32::<LittleEndian>()? as usize;
 
-        let db_buffer = cursor.into_inner();
-
         Ok(IP2Location{
             db_path: db_path.to_string(),
-            db_buffer: db_buffer,
+            db_buffer: Box::new(source),
             db_type: db_type,
             db_column: db_column,
             db_year: db_year,
@@ -73,19 +281,15 @@ impl IP2Location {
     }
 
     fn read_u32(&self, offset: usize) -> Result<u32, Error> {
-        let mut four_bytes = &self.db_buffer[offset - 1..offset + 3];
+        let bytes = self.db_buffer.read_at(offset - 1, 4)?;
+        let mut four_bytes = bytes.as_ref();
         Ok(four_bytes.read_u32::<LittleEndian>()?)
     }
 
     fn read_string(&self, offset: usize) -> Result<String, Error> {
-        let string_len = self.db_buffer[offset - 1] as usize;
-        let string_bytes = &self.db_buffer[offset..(offset + string_len)];
-        Ok(String::from_utf8(string_bytes.to_vec())?)
-    }
-
-    fn read_f32(&self, offset: usize) -> Result<f32, Error> {
-        let mut four_bytes = &self.db_buffer[offset - 1..offset + 3];
-        Ok(four_bytes.read_f32::<LittleEndian>()?)
+        let string_len = self.db_buffer.read_at(offset - 1, 1)?[0] as usize;
+        let string_bytes = self.db_buffer.read_at(offset, string_len)?;
+        Ok(String::from_utf8(string_bytes.into_owned())?)
     }
 
     fn read_ipv4(&self, offset: usize) -> Result<u32, Error> {
@@ -100,7 +304,7 @@ impl IP2Location {
         Ok((d << 96) | (c << 64) | (b << 32) | a)
     }
 
-    fn read_record(&self, ipaddr: IpAddr, base_db_addr: usize, offset: usize, index: usize) -> Result<Option<IP2LocationRecord>, Error> {
+    fn read_record(&self, ipaddr: IpAddr, base_db_addr: usize, offset: usize, index: usize, fields: RecordFields, ip_from: u128, ip_to: u128) -> Result<Option<IP2LocationRecord>, Error> {
         let mut rec = IP2LocationRecord{..Default::default()};
 
         match ipaddr {
@@ -110,101 +314,124 @@ impl IP2Location {
                         self.read_ipv4(self.ipv4_db_addr + (index) * self.db_column * 4)?
                     ).to_string()
                 );
+                rec.ip_from = Some(Ipv4Addr::from(ip_from as u32).to_string());
+                rec.ip_to = Some(Ipv4Addr::from(ip_to as u32).to_string());
+                rec.cidr = format_cidr_v4(ip_from as u32, ip_to as u32);
             }
             IpAddr::V6(_) => {
                 rec.ip = Some(
                     Ipv6Addr::from(
-                        self.read_ipv6(self.ipv6_db_addr + (index) * self.db_column * 4)?
+                        self.read_ipv6(self.ipv6_db_addr + index * (self.db_column * 4 + 12))?
                     ).to_string()
                 );
+                rec.ip_from = Some(Ipv6Addr::from(ip_from).to_string());
+                rec.ip_to = Some(Ipv6Addr::from(ip_to).to_string());
+                rec.cidr = format_cidr_v6(ip_from, ip_to);
             }
         }
 
-        let calc_off = |what: [usize; 25], index: usize| {
-            base_db_addr + index * (self.db_column * 4 + offset) + offset + 4 * (what[self.db_type] - 1)
+        // Slice the whole fixed-width row once, so every column pointer
+        // below is decoded from this local buffer instead of a separate
+        // `read_u32` (and thus a separate buffer access) per field.
+        let row_addr = base_db_addr + index * (self.db_column * 4 + offset) + offset;
+        let row = self.db_buffer.read_at(row_addr - 1, self.db_column * 4)?;
+        let col_ptr = |what: [usize; 25]| -> u32 {
+            let start = 4 * (what[self.db_type] - 1);
+            LittleEndian::read_u32(&row[start..start + 4])
         };
 
-        if positions::COUNTRY[self.db_type] != 0 {
-            rec.country_short = Some(self.read_string(self.read_u32(calc_off(positions::COUNTRY, index))? as usize + 1)?);
-            rec.country_long = Some(self.read_string(self.read_u32(calc_off(positions::COUNTRY, index))? as usize + 4)?);
+        if positions::COUNTRY[self.db_type] != 0 && fields.contains(RecordFields::COUNTRY) {
+            let ptr = col_ptr(positions::COUNTRY) as usize;
+            rec.country_short = Some(self.read_string(ptr + 1)?);
+            rec.country_long = Some(self.read_string(ptr + 4)?);
         }
 
-        if positions::REGION[self.db_type] != 0 {
-            rec.region = Some(self.read_string(self.read_u32(calc_off(positions::REGION, index))? as usize + 1)?);
+        if positions::REGION[self.db_type] != 0 && fields.contains(RecordFields::REGION) {
+            rec.region = Some(self.read_string(col_ptr(positions::REGION) as usize + 1)?);
         }
 
-        if positions::CITY[self.db_type] != 0 {
-            rec.city = Some(self.read_string(self.read_u32(calc_off(positions::CITY, index))? as usize + 1)?);
+        if positions::CITY[self.db_type] != 0 && fields.contains(RecordFields::CITY) {
+            rec.city = Some(self.read_string(col_ptr(positions::CITY) as usize + 1)?);
         }
 
-        if positions::ISP[self.db_type] != 0 {
-            rec.isp = Some(self.read_string(self.read_u32(calc_off(positions::ISP, index))? as usize + 1)?);
+        if positions::ISP[self.db_type] != 0 && fields.contains(RecordFields::ISP) {
+            rec.isp = Some(self.read_string(col_ptr(positions::ISP) as usize + 1)?);
         }
 
-        if positions::LATITUDE[self.db_type] != 0 {
-            rec.latitude = Some(self.read_f32(calc_off(positions::LATITUDE, index))?);
+        if positions::LATITUDE[self.db_type] != 0 && fields.contains(RecordFields::LATITUDE) {
+            rec.latitude = Some(f32::from_bits(col_ptr(positions::LATITUDE)));
         }
 
-        if positions::LONGITUDE[self.db_type] != 0 {
-            rec.longitude = Some(self.read_f32(calc_off(positions::LONGITUDE, index))?);
+        if positions::LONGITUDE[self.db_type] != 0 && fields.contains(RecordFields::LONGITUDE) {
+            rec.longitude = Some(f32::from_bits(col_ptr(positions::LONGITUDE)));
         }
 
-        if positions::DOMAIN[self.db_type] != 0 {
-            rec.domain = Some(self.read_string(self.read_u32(calc_off(positions::DOMAIN, index))? as usize + 1)?);
+        if positions::DOMAIN[self.db_type] != 0 && fields.contains(RecordFields::DOMAIN) {
+            rec.domain = Some(self.read_string(col_ptr(positions::DOMAIN) as usize + 1)?);
         }
 
-        if positions::ZIPCODE[self.db_type] != 0 {
-            rec.zipcode = Some(self.read_string(self.read_u32(calc_off(positions::ZIPCODE, index))? as usize + 1)?);
+        if positions::ZIPCODE[self.db_type] != 0 && fields.contains(RecordFields::ZIPCODE) {
+            rec.zipcode = Some(self.read_string(col_ptr(positions::ZIPCODE) as usize + 1)?);
         }
 
-        if positions::TIMEZONE[self.db_type] != 0 {
-            rec.timezone = Some(self.read_string(self.read_u32(calc_off(positions::TIMEZONE, index))? as usize + 1)?);
+        if positions::TIMEZONE[self.db_type] != 0 && fields.contains(RecordFields::TIMEZONE) {
+            rec.timezone = Some(self.read_string(col_ptr(positions::TIMEZONE) as usize + 1)?);
         }
 
-        if positions::NETSPEED[self.db_type] != 0 {
-            rec.netspeed = Some(self.read_string(self.read_u32(calc_off(positions::NETSPEED, index))? as usize + 1)?);
+        if positions::NETSPEED[self.db_type] != 0 && fields.contains(RecordFields::NETSPEED) {
+            rec.netspeed = Some(self.read_string(col_ptr(positions::NETSPEED) as usize + 1)?);
         }
 
-        if positions::IDDCODE[self.db_type] != 0 {
-            rec.iddcode = Some(self.read_string(self.read_u32(calc_off(positions::IDDCODE, index))? as usize + 1)?);
+        if positions::IDDCODE[self.db_type] != 0 && fields.contains(RecordFields::IDDCODE) {
+            rec.iddcode = Some(self.read_string(col_ptr(positions::IDDCODE) as usize + 1)?);
         }
 
-        if positions::AREACODE[self.db_type] != 0 {
-            rec.area_code = Some(self.read_string(self.read_u32(calc_off(positions::AREACODE, index))? as usize + 1)?);
+        if positions::AREACODE[self.db_type] != 0 && fields.contains(RecordFields::AREACODE) {
+            rec.area_code = Some(self.read_string(col_ptr(positions::AREACODE) as usize + 1)?);
         }
 
-        if positions::WEATHERSTATIONCODE[self.db_type] != 0 {
-            rec.weather_code = Some(self.read_string(self.read_u32(calc_off(positions::WEATHERSTATIONCODE, index))? as usize + 1)?);
+        if positions::WEATHERSTATIONCODE[self.db_type] != 0 && fields.contains(RecordFields::WEATHERSTATIONCODE) {
+            rec.weather_code = Some(self.read_string(col_ptr(positions::WEATHERSTATIONCODE) as usize + 1)?);
         }
 
-        if positions::WEATHERSTATIONNAME[self.db_type] != 0 {
-            rec.weather_name = Some(self.read_string(self.read_u32(calc_off(positions::WEATHERSTATIONNAME, index))? as usize + 1)?);
+        if positions::WEATHERSTATIONNAME[self.db_type] != 0 && fields.contains(RecordFields::WEATHERSTATIONNAME) {
+            rec.weather_name = Some(self.read_string(col_ptr(positions::WEATHERSTATIONNAME) as usize + 1)?);
         }
 
-        if positions::MCC[self.db_type] != 0 {
-            rec.mcc = Some(self.read_string(self.read_u32(calc_off(positions::MCC, index))? as usize + 1)?);
+        if positions::MCC[self.db_type] != 0 && fields.contains(RecordFields::MCC) {
+            rec.mcc = Some(self.read_string(col_ptr(positions::MCC) as usize + 1)?);
         }
 
-        if positions::MNC[self.db_type] != 0 {
-            rec.mnc = Some(self.read_string(self.read_u32(calc_off(positions::MNC, index))? as usize + 1)?);
+        if positions::MNC[self.db_type] != 0 && fields.contains(RecordFields::MNC) {
+            rec.mnc = Some(self.read_string(col_ptr(positions::MNC) as usize + 1)?);
         }
 
-        if positions::MOBILEBRAND[self.db_type] != 0 {
-            rec.mobile_brand = Some(self.read_string(self.read_u32(calc_off(positions::MOBILEBRAND, index))? as usize + 1)?);
+        if positions::MOBILEBRAND[self.db_type] != 0 && fields.contains(RecordFields::MOBILEBRAND) {
+            rec.mobile_brand = Some(self.read_string(col_ptr(positions::MOBILEBRAND) as usize + 1)?);
         }
 
-        if positions::ELEVATION[self.db_type] != 0 {
-            rec.elevation = Some(self.read_string(self.read_u32(calc_off(positions::ELEVATION, index))? as usize + 1)?);
+        if positions::ELEVATION[self.db_type] != 0 && fields.contains(RecordFields::ELEVATION) {
+            rec.elevation = Some(self.read_string(col_ptr(positions::ELEVATION) as usize + 1)?);
         }
 
-        if positions::USAGETYPE[self.db_type] != 0 {
-            rec.usage_type = Some(self.read_string(self.read_u32(calc_off(positions::USAGETYPE, index))? as usize + 1)?);
+        if positions::USAGETYPE[self.db_type] != 0 && fields.contains(RecordFields::USAGETYPE) {
+            rec.usage_type = Some(self.read_string(col_ptr(positions::USAGETYPE) as usize + 1)?);
         }
 
         Ok(Some(rec))
     }
 
     pub fn get_record(&self, ip_str: &str) -> Result<Option<IP2LocationRecord>, Error> {
+        self.get_record_with(ip_str, RecordFields::all())
+    }
+
+    /// Like `get_record`, but only decodes the columns selected by `fields`.
+    ///
+    /// Unselected columns skip both the pointer dereference and the UTF-8
+    /// decode, which matters when only a handful of fields are needed out of
+    /// a wide record (e.g. `RecordFields::COUNTRY` for country-only
+    /// filtering).
+    pub fn get_record_with(&self, ip_str: &str, fields: RecordFields) -> Result<Option<IP2LocationRecord>, Error> {
         let offset;
         let mut low = 0;
         let mut high;
@@ -228,9 +455,15 @@ impl IP2Location {
                     let ipto = self.read_ipv4(base_db_addr + (mid + 1) * (self.db_column * 4 + offset))?;
                     Ok((ipfrom, ipto))
                 };
-                self.binary_search(low, high, base_db_addr, offset, ipaddr, ipno, get_ip_range)
+                self.binary_search(low, high, base_db_addr, offset, ipaddr, ipno, fields, get_ip_range, |f, t| (f as u128, t as u128))
             }
             IpAddr::V6(ipaddrv6) => {
+                if self.ipv4_db_count > 0 {
+                    if let Some(ipv4) = embedded_ipv4(ipaddrv6) {
+                        return self.get_record_with(&ipv4.to_string(), fields);
+                    }
+                }
+
                 if self.ipv6_db_count == 0 {
                     return Err(err_msg("Please use IPv6 BIN file for IPv6 Address."));
                 }
@@ -250,14 +483,15 @@ impl IP2Location {
                     let ipto = self.read_ipv6(base_db_addr + (mid + 1) * (self.db_column * 4 + offset))?;
                     Ok((ipfrom, ipto))
                 };
-                self.binary_search(low, high, base_db_addr, offset, ipaddr, ipno, get_ip_range)
+                self.binary_search(low, high, base_db_addr, offset, ipaddr, ipno, fields, get_ip_range, |f, t| (f, t))
             }
         }
     }
 
-    fn binary_search<T, F>(&self, low: usize, high: usize, base_db_addr: usize, offset: usize, ipaddr: IpAddr, ipno: T, get_ip_range: F) -> Result<Option<IP2LocationRecord>, Error>
+    fn binary_search<T, F, G>(&self, low: usize, high: usize, base_db_addr: usize, offset: usize, ipaddr: IpAddr, ipno: T, fields: RecordFields, get_ip_range: F, to_u128: G) -> Result<Option<IP2LocationRecord>, Error>
         where T: PrimInt,
-              F: Fn(usize) -> Result<(T, T), Error> {
+              F: Fn(usize) -> Result<(T, T), Error>,
+              G: Fn(T, T) -> (u128, u128) {
 
         let mut low = low;
         let mut high = high;
@@ -266,10 +500,17 @@ impl IP2Location {
             let (ipfrom, ipto) = get_ip_range(mid)?;
 
             if ipfrom <= ipno && ipno < ipto {
-                return Ok(self.read_record(ipaddr, base_db_addr, offset, mid)?);
+                let (ip_from, ip_to) = to_u128(ipfrom, ipto);
+                return Ok(self.read_record(ipaddr, base_db_addr, offset, mid, fields, ip_from, ip_to)?);
             }
             else {
                 if ipno < ipfrom {
+                    // `mid` is `usize`, so `mid - 1` would underflow once the
+                    // search narrows down to index 0 with no match; bail out
+                    // instead of wrapping.
+                    if mid == 0 {
+                        return Ok(None);
+                    }
                     high = mid - 1
                 }
                 else {
@@ -279,11 +520,210 @@ impl IP2Location {
         }
         Ok(None)
     }
+
+    fn read_row_v4(&self, index: usize) -> Result<(u32, u32, IP2LocationRecord), Error> {
+        let stride = self.db_column * 4;
+        let ipfrom = self.read_ipv4(self.ipv4_db_addr + index * stride)?;
+        let ipto = self.read_ipv4(self.ipv4_db_addr + (index + 1) * stride)?;
+        let ipaddr = IpAddr::V4(Ipv4Addr::from(ipfrom));
+        let rec = self.read_record(ipaddr, self.ipv4_db_addr, 0, index, RecordFields::all(), ipfrom as u128, ipto as u128)?
+            .ok_or_else(|| err_msg("read_record unexpectedly returned no record for a valid row"))?;
+        Ok((ipfrom, ipto, rec))
+    }
+
+    fn read_row_v6(&self, index: usize) -> Result<(u128, u128, IP2LocationRecord), Error> {
+        let stride = self.db_column * 4 + 12;
+        let ipfrom = self.read_ipv6(self.ipv6_db_addr + index * stride)?;
+        let ipto = self.read_ipv6(self.ipv6_db_addr + (index + 1) * stride)?;
+        let ipaddr = IpAddr::V6(Ipv6Addr::from(ipfrom));
+        let rec = self.read_record(ipaddr, self.ipv6_db_addr, 12, index, RecordFields::all(), ipfrom, ipto)?
+            .ok_or_else(|| err_msg("read_record unexpectedly returned no record for a valid row"))?;
+        Ok((ipfrom, ipto, rec))
+    }
+
+    /// Returns the index of the first row whose `ipto` is greater than
+    /// `target`, i.e. the first row that could possibly overlap a range
+    /// starting at `target`.
+    fn lower_bound_v4(&self, target: u64) -> Result<usize, Error> {
+        let stride = self.db_column * 4;
+        let mut low = 0;
+        let mut high = self.ipv4_db_count;
+        while low < high {
+            let mid = low + (high - low) / 2;
+            let ipto = self.read_ipv4(self.ipv4_db_addr + (mid + 1) * stride)? as u64;
+            if ipto <= target {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        Ok(low)
+    }
+
+    /// Returns the index of the first row whose `ipto` is greater than
+    /// `target`, i.e. the first row that could possibly overlap a range
+    /// starting at `target`.
+    fn lower_bound_v6(&self, target: u128) -> Result<usize, Error> {
+        let stride = self.db_column * 4 + 12;
+        let mut low = 0;
+        let mut high = self.ipv6_db_count;
+        while low < high {
+            let mid = low + (high - low) / 2;
+            let ipto = self.read_ipv6(self.ipv6_db_addr + (mid + 1) * stride)?;
+            if ipto <= target {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        Ok(low)
+    }
+
+    /// Walks every row of the IPv4 database in order, yielding
+    /// `(ip_from, ip_to, record)` for each network block.
+    pub fn iter_v4(&self) -> Ipv4RowIter {
+        Ipv4RowIter { db: self, index: 0, count: self.ipv4_db_count }
+    }
+
+    /// Walks every row of the IPv6 database in order, yielding
+    /// `(ip_from, ip_to, record)` for each network block.
+    pub fn iter_v6(&self) -> Ipv6RowIter {
+        Ipv6RowIter { db: self, index: 0, count: self.ipv6_db_count }
+    }
+
+    /// Binary-searches to the first DB row overlapping `cidr` (e.g.
+    /// `"10.0.0.0/8"` or `"2001:db8::/32"`), then walks forward until a
+    /// row's `ip_from` is past the end of the range.
+    pub fn query_range(&self, cidr: &str) -> Result<QueryRangeIter, Error> {
+        let mut parts = cidr.splitn(2, '/');
+        let addr_part = parts.next().ok_or_else(|| err_msg("invalid CIDR"))?;
+        let prefix_part = parts.next().ok_or_else(|| err_msg("CIDR must include a prefix length, e.g. \"10.0.0.0/8\""))?;
+        let addr = addr_part.parse::<IpAddr>()?;
+        let prefix_len = prefix_part.parse::<u32>()?;
+
+        match addr {
+            IpAddr::V4(addr) => {
+                if prefix_len > 32 {
+                    return Err(err_msg("IPv4 prefix length must be between 0 and 32"));
+                }
+                let host_bits = 32 - prefix_len;
+                let mask: u64 = if host_bits >= 32 { 0 } else { !0u64 << host_bits } & 0xffff_ffff;
+                let network = u32::from(addr) as u64 & mask;
+                let range_to = network + (1u64 << host_bits);
+
+                let index = self.lower_bound_v4(network)?;
+                Ok(QueryRangeIter::V4(Ipv4RowIter { db: self, index: index, count: self.ipv4_db_count }, range_to))
+            }
+            IpAddr::V6(addr) => {
+                if prefix_len > 128 {
+                    return Err(err_msg("IPv6 prefix length must be between 0 and 128"));
+                }
+                let host_bits = 128 - prefix_len;
+                let mask: u128 = if host_bits >= 128 { 0 } else { !0u128 << host_bits };
+                let network = u128::from(addr) & mask;
+                let range_to = network.checked_add(1u128.checked_shl(host_bits).unwrap_or(u128::max_value())).unwrap_or(u128::max_value());
+
+                let index = self.lower_bound_v6(network)?;
+                Ok(QueryRangeIter::V6(Ipv6RowIter { db: self, index: index, count: self.ipv6_db_count }, range_to))
+            }
+        }
+    }
+}
+
+/// Iterator over consecutive rows of the IPv4 database, as returned by
+/// `IP2Location::iter_v4`.
+pub struct Ipv4RowIter<'a> {
+    db: &'a IP2Location,
+    index: usize,
+    count: usize,
+}
+
+impl<'a> Iterator for Ipv4RowIter<'a> {
+    type Item = Result<(Ipv4Addr, Ipv4Addr, IP2LocationRecord), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.index >= self.count {
+            return None;
+        }
+        let index = self.index;
+        self.index += 1;
+        Some(self.db.read_row_v4(index).map(|(from, to, rec)| (Ipv4Addr::from(from), Ipv4Addr::from(to), rec)))
+    }
+}
+
+/// Iterator over consecutive rows of the IPv6 database, as returned by
+/// `IP2Location::iter_v6`.
+pub struct Ipv6RowIter<'a> {
+    db: &'a IP2Location,
+    index: usize,
+    count: usize,
 }
 
-#[derive(Debug, Default, Serialize)]
+impl<'a> Iterator for Ipv6RowIter<'a> {
+    type Item = Result<(Ipv6Addr, Ipv6Addr, IP2LocationRecord), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.index >= self.count {
+            return None;
+        }
+        let index = self.index;
+        self.index += 1;
+        Some(self.db.read_row_v6(index).map(|(from, to, rec)| (Ipv6Addr::from(from), Ipv6Addr::from(to), rec)))
+    }
+}
+
+/// Iterator over the DB rows overlapping a `query_range` CIDR block, halting
+/// once a row's `ip_from` is past the end of the requested range.
+pub enum QueryRangeIter<'a> {
+    V4(Ipv4RowIter<'a>, u64),
+    V6(Ipv6RowIter<'a>, u128),
+}
+
+impl<'a> Iterator for QueryRangeIter<'a> {
+    type Item = Result<(IpAddr, IpAddr, IP2LocationRecord), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            QueryRangeIter::V4(iter, range_to) => {
+                match iter.next() {
+                    Some(Ok((from, to, rec))) => {
+                        if (u32::from(from) as u64) >= *range_to {
+                            None
+                        } else {
+                            Some(Ok((IpAddr::V4(from), IpAddr::V4(to), rec)))
+                        }
+                    }
+                    Some(Err(e)) => Some(Err(e)),
+                    None => None,
+                }
+            }
+            QueryRangeIter::V6(iter, range_to) => {
+                match iter.next() {
+                    Some(Ok((from, to, rec))) => {
+                        if u128::from(from) >= *range_to {
+                            None
+                        } else {
+                            Some(Ok((IpAddr::V6(from), IpAddr::V6(to), rec)))
+                        }
+                    }
+                    Some(Err(e)) => Some(Err(e)),
+                    None => None,
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
 pub struct IP2LocationRecord {
     pub ip: Option<String>,
+    /// Start of the matched network range.
+    pub ip_from: Option<String>,
+    /// End of the matched network range (exclusive).
+    pub ip_to: Option<String>,
+    /// The `[ip_from, ip_to)` range collapsed into the minimal set of CIDR
+    /// blocks, or the single prefix when the range is already aligned.
+    pub cidr: Option<String>,
     pub country_short: Option<String>,
     pub country_long: Option<String>,
     pub region: Option<String>,
@@ -311,6 +751,26 @@ mod tests {
     use super::*;
     use std::path::PathBuf;
 
+    #[test]
+    fn test_embedded_ipv4() {
+        let cases = vec![
+            ("::ffff:1.2.3.4", Some(Ipv4Addr::new(1, 2, 3, 4))),
+            ("::1.2.3.4", Some(Ipv4Addr::new(1, 2, 3, 4))),
+            ("2002:0102:0304::", Some(Ipv4Addr::new(1, 2, 3, 4))),
+            // Well-known Teredo example (RFC 4380): client IP 192.0.2.45,
+            // XOR-obfuscated into the low 32 bits.
+            ("2001:0000:4136:e378:8000:63bf:3fff:fdd2", Some(Ipv4Addr::new(192, 0, 2, 45))),
+            ("2606:4700:4700::1111", None),
+            ("::", None),
+            ("::1", None),
+        ];
+
+        for (addr, expected) in cases {
+            let v6: Ipv6Addr = addr.parse().unwrap();
+            assert_eq!(embedded_ipv4(v6), expected, "address {}", addr);
+        }
+    }
+
     #[test]
     fn test_ipv4() {
         let test_cases = vec![
@@ -360,4 +820,243 @@ mod tests {
             assert_eq!(record.country_short.unwrap(), country_short);
         }
     }
+
+    /// Regression test for the `binary_search` underflow: a query below the
+    /// lowest indexed range used to narrow `high` down to `mid == 0` and
+    /// then compute `mid - 1` on a `usize`, panicking instead of reporting
+    /// "no match". Builds a minimal single-row IP-COUNTRY-shaped database
+    /// in memory (via `open_bytes`) rather than depending on a `.BIN`
+    /// fixture, since the bug is in the search itself, not the row format.
+    #[test]
+    fn test_binary_search_no_match_below_range_does_not_panic() {
+        let mut bytes = vec![0u8; 80];
+        bytes[0] = 1; // db_type: IP-COUNTRY
+        bytes[1] = 2; // db_column: ip + country pointer
+        LittleEndian::write_u32(&mut bytes[5..9], 1); // ipv4_db_count
+        LittleEndian::write_u32(&mut bytes[9..13], 65); // ipv4_db_addr (1-based)
+
+        // Row 0: ip_from = 256. Row 1 (sentinel): ip_from = 512, i.e. the
+        // upper bound of row 0's range.
+        LittleEndian::write_u32(&mut bytes[64..68], 256);
+        LittleEndian::write_u32(&mut bytes[72..76], 512);
+
+        let database = IP2Location::open_bytes("synthetic", bytes).unwrap();
+        assert!(database.get_record("0.0.0.1").unwrap().is_none());
+    }
+
+    /// Builds a minimal in-memory IP-COUNTRY-shaped (`db_type` 1) IPv4
+    /// database: one row per `(ip_from, country_short, country_long)` entry,
+    /// plus `sentinel_ip` as the final row's exclusive upper bound, the way
+    /// a real `.BIN` file terminates its row table.
+    fn build_ipv4_country_db(ranges: &[(u32, &str, &str)], sentinel_ip: u32) -> Vec<u8> {
+        let db_column = 2usize; // columns: ip, country pointer
+        let row_count = ranges.len();
+        let header_len = 64;
+        let rows_len = (row_count + 1) * db_column * 4;
+        let data_start = header_len + rows_len;
+
+        let mut country_block = Vec::new();
+        let mut country_ptrs = Vec::with_capacity(row_count);
+        for &(_, short, long) in ranges {
+            let ptr = data_start + country_block.len();
+            country_ptrs.push(ptr as u32);
+            country_block.push(short.len() as u8);
+            country_block.extend_from_slice(short.as_bytes());
+            country_block.push(long.len() as u8);
+            country_block.extend_from_slice(long.as_bytes());
+        }
+
+        let mut bytes = vec![0u8; data_start + country_block.len()];
+        bytes[0] = 1; // db_type: IP-COUNTRY
+        bytes[1] = db_column as u8;
+        LittleEndian::write_u32(&mut bytes[5..9], row_count as u32); // ipv4_db_count
+        LittleEndian::write_u32(&mut bytes[9..13], (header_len + 1) as u32); // ipv4_db_addr (1-based)
+
+        for (i, &(ip_from, _, _)) in ranges.iter().enumerate() {
+            let row_off = header_len + i * db_column * 4;
+            LittleEndian::write_u32(&mut bytes[row_off..row_off + 4], ip_from);
+            LittleEndian::write_u32(&mut bytes[row_off + 4..row_off + 8], country_ptrs[i]);
+        }
+        let sentinel_off = header_len + row_count * db_column * 4;
+        LittleEndian::write_u32(&mut bytes[sentinel_off..sentinel_off + 4], sentinel_ip);
+
+        bytes[data_start..].copy_from_slice(&country_block);
+        bytes
+    }
+
+    #[test]
+    fn test_get_record_with_masks_unselected_fields() {
+        let bytes = build_ipv4_country_db(&[(16_777_216, "US", "United States")], 16_777_217);
+        let database = IP2Location::open_bytes("synthetic", bytes).unwrap();
+
+        let full = database.get_record("1.0.0.0").unwrap().unwrap();
+        assert_eq!(full.country_short.as_deref(), Some("US"));
+        assert_eq!(full.country_long.as_deref(), Some("United States"));
+
+        let masked = database.get_record_with("1.0.0.0", RecordFields::empty()).unwrap().unwrap();
+        assert_eq!(masked.country_short, None);
+        assert_eq!(masked.country_long, None);
+        // ip/ip_from/ip_to/cidr aren't gated by RecordFields.
+        assert_eq!(masked.ip.as_deref(), Some("1.0.0.0"));
+        assert_eq!(masked.ip_from.as_deref(), Some("1.0.0.0"));
+
+        let country_only = database.get_record_with("1.0.0.0", RecordFields::COUNTRY).unwrap().unwrap();
+        assert_eq!(country_only.country_short.as_deref(), Some("US"));
+    }
+
+    #[test]
+    fn test_ip2location_is_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<IP2Location>();
+    }
+
+    #[test]
+    fn test_open_mmap_and_open_bytes_agree() {
+        let bytes = build_ipv4_country_db(&[(16_777_216, "JP", "Japan")], 16_777_217);
+
+        let mut path = std::env::temp_dir();
+        path.push(format!("ip2location_rs_test_{}.bin", std::process::id()));
+        std::fs::write(&path, &bytes).unwrap();
+
+        let mmap_db = IP2Location::open_mmap(path.to_str().unwrap()).unwrap();
+        let bytes_db = IP2Location::open_bytes("synthetic", bytes).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        let from_mmap = mmap_db.get_record("1.0.0.0").unwrap().unwrap();
+        let from_bytes = bytes_db.get_record("1.0.0.0").unwrap().unwrap();
+        assert_eq!(from_mmap.country_short, from_bytes.country_short);
+        assert_eq!(from_mmap.country_long, from_bytes.country_long);
+        assert_eq!(from_mmap.ip_from, from_bytes.ip_from);
+        assert_eq!(from_mmap.ip_to, from_bytes.ip_to);
+    }
+
+    #[test]
+    fn test_cached_ip2location_hit_eviction_and_field_reprojection() {
+        let bytes = build_ipv4_country_db(
+            &[(1, "AA", "Alpha"), (2, "BB", "Beta"), (3, "CC", "Gamma")],
+            4,
+        );
+        let database = IP2Location::open_bytes("synthetic", bytes).unwrap().with_cache(2);
+
+        let miss = database.get_record("0.0.0.1").unwrap().unwrap();
+        assert_eq!(miss.country_short.as_deref(), Some("AA"));
+        let (v4_stats, _) = database.cache_stats();
+        assert_eq!((v4_stats.hits, v4_stats.misses), (0, 1));
+
+        // Same range, narrower field selection: served from the cache, but
+        // still masked down to what was asked for.
+        let hit = database.get_record_with("0.0.0.1", RecordFields::empty()).unwrap().unwrap();
+        assert_eq!(hit.country_short, None);
+        let (v4_stats, _) = database.cache_stats();
+        assert_eq!((v4_stats.hits, v4_stats.misses), (1, 1));
+
+        // Pull in two more distinct ranges, pushing the capacity-2 cache
+        // past its limit and evicting the first range.
+        database.get_record("0.0.0.2").unwrap();
+        database.get_record("0.0.0.3").unwrap();
+        let (v4_stats, _) = database.cache_stats();
+        assert_eq!((v4_stats.hits, v4_stats.misses), (1, 3));
+
+        // The first range was evicted, so this is a miss again, not a hit.
+        database.get_record("0.0.0.1").unwrap();
+        let (v4_stats, _) = database.cache_stats();
+        assert_eq!((v4_stats.hits, v4_stats.misses), (1, 4));
+    }
+
+    /// Builds a minimal in-memory IP-COUNTRY-shaped (`db_type` 1) IPv6
+    /// database, laid out the same way as `build_ipv4_country_db` but with
+    /// the wider 16-byte-per-row IP field.
+    fn build_ipv6_country_db(ranges: &[(u128, &str, &str)], sentinel_ip: u128) -> Vec<u8> {
+        let db_column = 2usize; // columns: ip, country pointer
+        let stride = db_column * 4 + 12;
+        let row_count = ranges.len();
+        let header_len = 64;
+        let rows_len = (row_count + 1) * stride;
+        let data_start = header_len + rows_len;
+
+        let mut country_block = Vec::new();
+        let mut country_ptrs = Vec::with_capacity(row_count);
+        for &(_, short, long) in ranges {
+            let ptr = data_start + country_block.len();
+            country_ptrs.push(ptr as u32);
+            country_block.push(short.len() as u8);
+            country_block.extend_from_slice(short.as_bytes());
+            country_block.push(long.len() as u8);
+            country_block.extend_from_slice(long.as_bytes());
+        }
+
+        let mut bytes = vec![0u8; data_start + country_block.len()];
+        bytes[0] = 1; // db_type: IP-COUNTRY
+        bytes[1] = db_column as u8;
+        LittleEndian::write_u32(&mut bytes[13..17], row_count as u32); // ipv6_db_count
+        LittleEndian::write_u32(&mut bytes[17..21], (header_len + 1) as u32); // ipv6_db_addr (1-based)
+
+        let write_ip = |bytes: &mut [u8], off: usize, ip: u128| {
+            LittleEndian::write_u32(&mut bytes[off..off + 4], ip as u32);
+            LittleEndian::write_u32(&mut bytes[off + 4..off + 8], (ip >> 32) as u32);
+            LittleEndian::write_u32(&mut bytes[off + 8..off + 12], (ip >> 64) as u32);
+            LittleEndian::write_u32(&mut bytes[off + 12..off + 16], (ip >> 96) as u32);
+        };
+
+        for (i, &(ip_from, _, _)) in ranges.iter().enumerate() {
+            let row_off = header_len + i * stride;
+            write_ip(&mut bytes, row_off, ip_from);
+            LittleEndian::write_u32(&mut bytes[row_off + 16..row_off + 20], country_ptrs[i]);
+        }
+        let sentinel_off = header_len + row_count * stride;
+        write_ip(&mut bytes, sentinel_off, sentinel_ip);
+
+        bytes[data_start..].copy_from_slice(&country_block);
+        bytes
+    }
+
+    #[test]
+    fn test_iter_v4_walks_every_row_in_order() {
+        let ranges = [(0u32, "AA", "Alpha"), (256, "BB", "Beta"), (512, "CC", "Gamma")];
+        let bytes = build_ipv4_country_db(&ranges, 768);
+        let database = IP2Location::open_bytes("synthetic", bytes).unwrap();
+
+        let rows: Vec<_> = database.iter_v4().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(rows.len(), 3);
+        for (i, (from, to, rec)) in rows.iter().enumerate() {
+            assert_eq!(*from, Ipv4Addr::from(ranges[i].0));
+            assert_eq!(rec.ip.as_deref(), Some(Ipv4Addr::from(ranges[i].0).to_string()).as_deref());
+            assert_eq!(rec.country_short.as_deref(), Some(ranges[i].1));
+            let expected_to = ranges.get(i + 1).map(|r| r.0).unwrap_or(768);
+            assert_eq!(*to, Ipv4Addr::from(expected_to));
+        }
+    }
+
+    /// Regression test for the chunk0-6 bug where `read_record`'s
+    /// `IpAddr::V6` arm read `rec.ip` with the v4 row stride
+    /// (`db_column * 4`) instead of the v6 stride
+    /// (`db_column * 4 + 12`), drifting into a neighboring row's column
+    /// data for every row past index 0.
+    #[test]
+    fn test_iter_v6_reads_rec_ip_at_the_correct_stride() {
+        let base = 0x2001_0db8_0000_0000_0000_0000_0000_0000u128;
+        let ranges = [
+            (base, "AA", "Alpha"),
+            (base + 0x100, "BB", "Beta"),
+            (base + 0x200, "CC", "Gamma"),
+        ];
+        let bytes = build_ipv6_country_db(&ranges, base + 0x300);
+        let database = IP2Location::open_bytes("synthetic", bytes).unwrap();
+
+        let rows: Vec<_> = database.iter_v6().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(rows.len(), 3);
+        for (i, (from, _to, rec)) in rows.iter().enumerate() {
+            assert_eq!(*from, Ipv6Addr::from(ranges[i].0));
+            assert_eq!(rec.ip.as_deref(), Some(Ipv6Addr::from(ranges[i].0).to_string()).as_deref());
+            assert_eq!(rec.country_short.as_deref(), Some(ranges[i].1));
+        }
+
+        // query_range("2001:db8::100/120") should binary-search straight to
+        // the single row covering [base+0x100, base+0x200).
+        let matched: Vec<_> = database.query_range("2001:db8::100/120").unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(matched.len(), 1);
+        let (from, _to, rec) = &matched[0];
+        assert_eq!(*from, IpAddr::V6(Ipv6Addr::from(base + 0x100)));
+        assert_eq!(rec.country_short.as_deref(), Some("BB"));
+    }
 }
@@ -0,0 +1,36 @@
+use std::borrow::Cow;
+use std::fmt::Debug;
+
+use failure::Error;
+use memmap::Mmap;
+
+/// Abstracts over where the `.BIN` database bytes live, so the binary-search
+/// and record-decoding logic in `lib.rs` can run unchanged whether the
+/// database is memory-mapped, held entirely in memory, or was streamed in
+/// from some other source.
+///
+/// `Send + Sync` so `Box<dyn DbSource>` (and thus `IP2Location`) stays safe
+/// to share across threads, e.g. behind an `Arc`, the way the bare `Mmap`
+/// field it replaced always was.
+pub trait DbSource: Debug + Send + Sync {
+    /// Returns the `len` bytes starting at `offset`.
+    fn read_at(&self, offset: usize, len: usize) -> Result<Cow<[u8]>, Error>;
+}
+
+#[derive(Debug)]
+pub struct MmapSource(pub(crate) Mmap);
+
+impl DbSource for MmapSource {
+    fn read_at(&self, offset: usize, len: usize) -> Result<Cow<[u8]>, Error> {
+        Ok(Cow::Borrowed(&self.0[offset..offset + len]))
+    }
+}
+
+#[derive(Debug)]
+pub struct BytesSource(pub(crate) Vec<u8>);
+
+impl DbSource for BytesSource {
+    fn read_at(&self, offset: usize, len: usize) -> Result<Cow<[u8]>, Error> {
+        Ok(Cow::Borrowed(&self.0[offset..offset + len]))
+    }
+}